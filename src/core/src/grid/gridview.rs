@@ -1,9 +1,16 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
 use rand::{IsaacRng, Rng};
 use rand::distributions::Normal;
+use rstar::{RTree, RTreeObject, AABB};
 
 use super::{Droplet, DropletId, DropletInfo, Grid, Location};
 
 use exec::Action;
+use plan::chunked::{ChunkConfig, PathCache};
+use plan::route::DistanceCache;
 
 use process::ProcessId;
 use util::collections::Map;
@@ -14,6 +21,85 @@ pub struct GridView {
     pub droplets: Map<DropletId, Droplet>,
     rng: IsaacRng,
     split_error_stdev: Option<Normal>,
+    pub(crate) distance_cache: DistanceCache,
+    pub(crate) chunk_cache: RefCell<Map<ChunkConfig, Rc<PathCache>>>,
+    spatial_index: SpatialIndex,
+}
+
+/// An entry in the `SpatialIndex` r-tree: a droplet's occupied rectangle (the bounding box
+/// of `Droplet::get_locations`), keyed by `DropletId` so collision queries can skip
+/// same-`collision_group` pairs without a second lookup.
+#[derive(Debug, Clone, Copy)]
+struct DropletEnvelope {
+    id: DropletId,
+    collision_group: usize,
+    envelope: AABB<[i32; 2]>,
+}
+
+impl RTreeObject for DropletEnvelope {
+    type Envelope = AABB<[i32; 2]>;
+
+    fn envelope(&self) -> AABB<[i32; 2]> {
+        self.envelope
+    }
+}
+
+fn bounding_envelope(locations: &[Location]) -> AABB<[i32; 2]> {
+    let min_y = locations.iter().map(|l| l.y as i32).min().unwrap();
+    let max_y = locations.iter().map(|l| l.y as i32).max().unwrap();
+    let min_x = locations.iter().map(|l| l.x as i32).min().unwrap();
+    let max_x = locations.iter().map(|l| l.x as i32).max().unwrap();
+    AABB::from_corners([min_y, min_x], [max_y, max_x])
+}
+
+/// An r-tree over droplet *bounding boxes*, rebuilt lazily whenever `GridView::execute`
+/// inserts, removes, or moves a droplet. Used purely as a broad-phase filter by
+/// `get_collision` (`get_destination_collision` bulk-loads its own r-tree over droplet
+/// destinations on every call instead): turns it from an O(n^2) pairwise scan into an
+/// O(n log n) set of envelope queries over a small set of candidates, each of which still
+/// gets an exact cell-membership check, since a grown footprint can be non-rectangular on a
+/// grid with non-walkable cells and so isn't exactly described by its bounding box.
+struct SpatialIndex(RefCell<Option<Rc<RTree<DropletEnvelope>>>>);
+
+impl Default for SpatialIndex {
+    fn default() -> SpatialIndex {
+        SpatialIndex(RefCell::new(None))
+    }
+}
+
+impl Clone for SpatialIndex {
+    fn clone(&self) -> SpatialIndex {
+        // the cache is a derived, per-process structure; a clone just starts cold
+        SpatialIndex::default()
+    }
+}
+
+impl fmt::Debug for SpatialIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SpatialIndex {{ .. }}")
+    }
+}
+
+impl SpatialIndex {
+    fn invalidate(&self) {
+        *self.0.borrow_mut() = None;
+    }
+
+    fn get_or_build(&self, droplets: &Map<DropletId, Droplet>) -> Rc<RTree<DropletEnvelope>> {
+        let mut cache = self.0.borrow_mut();
+        if cache.is_none() {
+            let entries = droplets
+                .values()
+                .map(|d| DropletEnvelope {
+                    id: d.id,
+                    collision_group: d.collision_group,
+                    envelope: bounding_envelope(&Droplet::get_locations(&d.location, &d.dimensions)),
+                })
+                .collect();
+            *cache = Some(Rc::new(RTree::bulk_load(entries)));
+        }
+        cache.clone().unwrap()
+    }
 }
 
 #[derive(Default, Deserialize)]
@@ -29,6 +115,9 @@ impl GridView {
             droplets: Map::new(),
             rng: IsaacRng::new_from_u64(0),
             split_error_stdev: Some(Normal::new(0.0, opts.split_error_stdev)),
+            distance_cache: DistanceCache::default(),
+            chunk_cache: RefCell::new(Map::new()),
+            spatial_index: SpatialIndex::default(),
         }
     }
 
@@ -38,25 +127,29 @@ impl GridView {
 
     /// Returns an invalid droplet, if any.
     pub fn get_collision(&self) -> Option<(DropletId, DropletId)> {
+        let index = self.spatial_index.get_or_build(&self.droplets);
+
         for (id1, droplet1) in self.droplets.iter() {
-            for (id2, droplet2) in self.droplets.iter() {
-                if id1 == id2 {
+            let footprint1 = self.grid
+                .neighbors_dimensions(&droplet1.location, &droplet1.dimensions);
+            let query = bounding_envelope(&footprint1);
+
+            for candidate in index.locate_in_envelope_intersecting(&query) {
+                if candidate.id == *id1 {
                     continue;
                 }
-                if droplet1.collision_group == droplet2.collision_group {
+                if candidate.collision_group == droplet1.collision_group {
                     continue;
                 }
 
-                let collide = self.grid
-                    .neighbors_dimensions(&droplet1.location, &droplet1.dimensions)
-                    .into_iter()
-                    .any(|loc| {
-                        Droplet::get_locations(&droplet2.location, &droplet2.dimensions)
-                            .contains(&loc)
-                    });
-
-                if collide {
-                    return Some((*id1, *id2));
+                // the r-tree only narrows candidates by bounding-box overlap; a grid with
+                // non-walkable cells can make a droplet's grown footprint non-rectangular,
+                // so re-check true cell membership (the same test `neighbors_dimensions`
+                // overlap used before the r-tree existed) before declaring a collision
+                let droplet2 = &self.droplets[&candidate.id];
+                let occupied2 = Droplet::get_locations(&droplet2.location, &droplet2.dimensions);
+                if footprint1.iter().any(|loc| occupied2.contains(loc)) {
+                    return Some((*id1, candidate.id));
                 }
             }
         }
@@ -64,29 +157,46 @@ impl GridView {
     }
 
     pub fn get_destination_collision(&self) -> Option<(DropletId, DropletId)> {
-        for (id1, droplet1) in self.droplets.iter() {
-            for (id2, droplet2) in self.droplets.iter() {
-                if id1 == id2 {
-                    continue;
-                }
-                if droplet1.collision_group == droplet2.collision_group {
+        let destined: Vec<(DropletId, usize, Location)> = self.droplets
+            .values()
+            .filter_map(|d| d.destination.map(|dest| (d.id, d.collision_group, dest)))
+            .collect();
+
+        let dests: Map<DropletId, Location> =
+            destined.iter().map(|&(id, _, dest)| (id, dest)).collect();
+
+        let index = RTree::bulk_load(
+            destined
+                .iter()
+                .map(|&(id, collision_group, dest)| {
+                    let dims = self.droplets[&id].dimensions;
+                    DropletEnvelope {
+                        id,
+                        collision_group,
+                        envelope: bounding_envelope(&Droplet::get_locations(&dest, &dims)),
+                    }
+                })
+                .collect(),
+        );
+
+        for &(id1, collision_group1, dest1) in destined.iter() {
+            let dims1 = self.droplets[&id1].dimensions;
+            let footprint1 = self.grid.neighbors_dimensions(&dest1, &dims1);
+            let query = bounding_envelope(&footprint1);
+
+            for candidate in index.locate_in_envelope_intersecting(&query) {
+                if candidate.id == id1 {
                     continue;
                 }
-
-                if droplet1.destination.is_none() || droplet2.destination.is_none() {
+                if candidate.collision_group == collision_group1 {
                     continue;
                 }
 
-                let dest1 = droplet1.destination.unwrap();
-                let dest2 = droplet2.destination.unwrap();
-
-                let collide = self.grid
-                    .neighbors_dimensions(&dest1, &droplet1.dimensions)
-                    .into_iter()
-                    .any(|loc| Droplet::get_locations(&dest2, &droplet2.dimensions).contains(&loc));
-
-                if collide {
-                    return Some((*id1, *id2));
+                // same broad-phase-then-exact-check as get_collision, over destinations
+                let dims2 = self.droplets[&candidate.id].dimensions;
+                let occupied2 = Droplet::get_locations(&dests[&candidate.id], &dims2);
+                if footprint1.iter().any(|loc| occupied2.contains(loc)) {
+                    return Some((id1, candidate.id));
                 }
             }
         }
@@ -104,15 +214,21 @@ impl GridView {
     fn insert(&mut self, droplet: Droplet) {
         let was_there = self.droplets.insert(droplet.id, droplet);
         assert!(was_there.is_none());
+        self.spatial_index.invalidate();
     }
 
     fn remove(&mut self, id: DropletId) -> Droplet {
-        self.droplets
+        let droplet = self.droplets
             .remove(&id)
-            .expect(&format!("Tried to remove a non-existent droplet: {:?}", id))
+            .expect(&format!("Tried to remove a non-existent droplet: {:?}", id));
+        self.spatial_index.invalidate();
+        droplet
     }
 
     fn get_mut(&mut self, id: DropletId) -> &mut Droplet {
+        // callers use this to mutate a droplet's location, so the cached index is stale
+        // the moment the caller gets their hands on it
+        self.spatial_index.invalidate();
         self.droplets
             .get_mut(&id)
             .expect(&format!("Tried to get a non-existent droplet: {:?}", id))
@@ -199,6 +315,8 @@ pub mod tests {
 
     use std::ops::Range;
 
+    use grid::grid::tests::arb_grid;
+
     prop_compose! {
         fn arb_droplet_id()
             (id in prop::num::usize::ANY,
@@ -245,4 +363,71 @@ pub mod tests {
             })
             .boxed()
     }
+
+    /// Reference implementation of `get_collision`: an O(n^2) pairwise scan with no r-tree
+    /// involved, to check the `SpatialIndex`-backed version against.
+    fn naive_collision(gv: &GridView) -> Option<(DropletId, DropletId)> {
+        for (&id1, d1) in gv.droplets.iter() {
+            let footprint1 = gv.grid.neighbors_dimensions(&d1.location, &d1.dimensions);
+            for (&id2, d2) in gv.droplets.iter() {
+                if id1 == id2 || d1.collision_group == d2.collision_group {
+                    continue;
+                }
+                let occupied2 = Droplet::get_locations(&d2.location, &d2.dimensions);
+                if footprint1.iter().any(|loc| occupied2.contains(loc)) {
+                    return Some((id1, id2));
+                }
+            }
+        }
+        None
+    }
+
+    /// Reference implementation of `get_destination_collision`, same shape as
+    /// `naive_collision` but over destinations instead of current locations.
+    fn naive_destination_collision(gv: &GridView) -> Option<(DropletId, DropletId)> {
+        for (&id1, d1) in gv.droplets.iter() {
+            let dest1 = match d1.destination {
+                Some(dest) => dest,
+                None => continue,
+            };
+            let footprint1 = gv.grid.neighbors_dimensions(&dest1, &d1.dimensions);
+            for (&id2, d2) in gv.droplets.iter() {
+                if id1 == id2 || d1.collision_group == d2.collision_group {
+                    continue;
+                }
+                let dest2 = match d2.destination {
+                    Some(dest) => dest,
+                    None => continue,
+                };
+                let occupied2 = Droplet::get_locations(&dest2, &d2.dimensions);
+                if footprint1.iter().any(|loc| occupied2.contains(loc)) {
+                    return Some((id1, id2));
+                }
+            }
+        }
+        None
+    }
+
+    proptest! {
+        #[test]
+        fn get_collision_matches_naive_scan(
+            ref gv in arb_grid(5..10, 5..10, 0.95)
+                .prop_filter("not connected", |ref g| g.is_connected())
+                .prop_flat_map(|g| arb_gridview(g, 0..6))
+        ) {
+            prop_assert_eq!(gv.get_collision().is_some(), naive_collision(gv).is_some());
+        }
+
+        #[test]
+        fn get_destination_collision_matches_naive_scan(
+            ref gv in arb_grid(5..10, 5..10, 0.95)
+                .prop_filter("not connected", |ref g| g.is_connected())
+                .prop_flat_map(|g| arb_gridview(g, 0..6))
+        ) {
+            prop_assert_eq!(
+                gv.get_destination_collision().is_some(),
+                naive_destination_collision(gv).is_some()
+            );
+        }
+    }
 }
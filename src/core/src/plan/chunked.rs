@@ -0,0 +1,385 @@
+//! Hierarchical chunk-based routing for large grids.
+//!
+//! Plain `route_many` plans a full space-time A* per droplet over every cell of the grid,
+//! which scales poorly as grids grow. This module adds a coarser layer on top: the grid is
+//! partitioned into fixed-size rectangular chunks, the "gateway" cells where a chunk borders
+//! a walkable cell in an adjacent chunk are detected, and the shortest paths between
+//! gateways of the same chunk are precomputed and cached in a `PathCache`. Routing a droplet then
+//! means planning a short abstract path over the (tiny) gateway graph and stitching the cached
+//! intra-chunk paths together; the existing space-time `route_one` is only invoked afterwards,
+//! to locally repair the droplets whose stitched path the `AvoidanceSet` rejects.
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use grid::{Droplet, DropletId, Grid, GridView, Location};
+use plan::minheap::MinHeap;
+use plan::route::{route_one, AvoidanceSet, DistanceCache, Node, Path, RouteMode, Time};
+
+use util::collections::Map;
+
+impl GridView {
+    /// Routes every droplet using the hierarchical chunked planner instead of the plain
+    /// per-droplet space-time `route`. Gateway paths for `config` are built once per
+    /// `GridView` and reused across calls.
+    pub fn route_chunked(&self, config: ChunkConfig) -> Option<Map<DropletId, Path>> {
+        let cache = self.chunk_path_cache(config);
+        let droplets = self.droplets.iter().collect::<Vec<_>>();
+        route_many_chunked(&droplets, &self.grid, &cache, &self.distance_cache)
+    }
+
+    fn chunk_path_cache(&self, config: ChunkConfig) -> Rc<PathCache> {
+        let mut cache = self.chunk_cache.borrow_mut();
+        cache
+            .entry(config)
+            .or_insert_with(|| Rc::new(PathCache::build(&self.grid, config)))
+            .clone()
+    }
+}
+
+/// Trades precompute memory for routing speed: a smaller `chunk_size` finds more, shorter
+/// gateway chains (cheaper to build, more abstract hops to stitch); `cache_full_paths`
+/// controls whether intra-chunk paths are memoized or recomputed with a plain BFS on demand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkConfig {
+    pub chunk_size: usize,
+    pub cache_full_paths: bool,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> ChunkConfig {
+        ChunkConfig {
+            chunk_size: 8,
+            cache_full_paths: true,
+        }
+    }
+}
+
+type ChunkId = (i32, i32);
+
+fn chunk_of(loc: &Location, chunk_size: usize) -> ChunkId {
+    (
+        loc.y as i32 / chunk_size as i32,
+        loc.x as i32 / chunk_size as i32,
+    )
+}
+
+/// A gateway is a walkable cell on a chunk boundary with a walkable neighbor in a
+/// different chunk.
+fn find_gateways(grid: &Grid, config: &ChunkConfig) -> Map<ChunkId, Vec<Location>> {
+    let mut gateways: Map<ChunkId, Vec<Location>> = Map::new();
+
+    for (loc, _cell) in grid.locations() {
+        let here = chunk_of(&loc, config.chunk_size);
+        let is_gateway = grid
+            .neighbors4(&loc)
+            .iter()
+            .any(|n| chunk_of(n, config.chunk_size) != here);
+
+        if is_gateway {
+            gateways.entry(here).or_insert_with(Vec::new).push(loc);
+        }
+    }
+
+    gateways
+}
+
+/// Plain BFS over `neighbors4`, unaware of chunk boundaries or other droplets; used both to
+/// precompute intra-chunk gateway-to-gateway paths and, on demand, to connect a start or
+/// destination location to the gateways of its own chunk.
+fn bfs_within(grid: &Grid, from: Location, to: Location) -> Option<Path> {
+    if from == to {
+        return Some(vec![from]);
+    }
+
+    let mut came_from: Map<Location, Location> = Map::new();
+    let mut seen: Map<Location, bool> = Map::new();
+    let mut queue = VecDeque::new();
+
+    seen.insert(from, true);
+    queue.push_back(from);
+
+    while let Some(loc) = queue.pop_front() {
+        for next in grid.neighbors4(&loc) {
+            if seen.contains_key(&next) {
+                continue;
+            }
+            seen.insert(next, true);
+            came_from.insert(next, loc);
+
+            if next == to {
+                let mut path = vec![next];
+                let mut cur = next;
+                while let Some(&prev) = came_from.get(&cur) {
+                    path.push(prev);
+                    cur = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            queue.push_back(next);
+        }
+    }
+
+    None
+}
+
+fn path_cost(path: &Path) -> u32 {
+    (path.len().saturating_sub(1) as u32) * 100
+}
+
+/// Precomputed shortest paths between every pair of gateways that share a chunk, plus a
+/// one-step edge from each gateway to each adjacent gateway across a chunk boundary. The
+/// same-chunk paths come from `bfs_within`, which searches the whole grid, so such a path
+/// is not confined to the chunk its endpoints belong to.
+#[derive(Debug)]
+pub(crate) struct PathCache {
+    config: ChunkConfig,
+    gateways: Map<ChunkId, Vec<Location>>,
+    edges: Map<Location, Vec<(Location, u32, Option<Path>)>>,
+}
+
+impl PathCache {
+    pub(crate) fn build(grid: &Grid, config: ChunkConfig) -> PathCache {
+        let gateways = find_gateways(grid, &config);
+        let mut edges: Map<Location, Vec<(Location, u32, Option<Path>)>> = Map::new();
+
+        for locs in gateways.values() {
+            for &from in locs {
+                for &to in locs {
+                    if from == to {
+                        continue;
+                    }
+                    if let Some(path) = bfs_within(grid, from, to) {
+                        let cost = path_cost(&path);
+                        let cached = if config.cache_full_paths {
+                            Some(path)
+                        } else {
+                            None
+                        };
+                        edges.entry(from).or_insert_with(Vec::new).push((to, cost, cached));
+                    }
+                }
+            }
+        }
+
+        // link each gateway to its walkable neighbors in other chunks, so the abstract
+        // search can actually cross from one chunk into the next
+        for locs in gateways.values() {
+            for &from in locs {
+                let here = chunk_of(&from, config.chunk_size);
+                for to in grid.neighbors4(&from) {
+                    if chunk_of(&to, config.chunk_size) != here {
+                        let cached = if config.cache_full_paths { Some(vec![from, to]) } else { None };
+                        edges.entry(from).or_insert_with(Vec::new).push((to, 100, cached));
+                    }
+                }
+            }
+        }
+
+        PathCache {
+            config,
+            gateways,
+            edges,
+        }
+    }
+
+    fn neighbors(&self, gateway: &Location) -> &[(Location, u32, Option<Path>)] {
+        self.edges.get(gateway).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+/// Plans an abstract path over the gateway graph from `start` to `dest` and stitches the
+/// cached (or freshly BFS'd) intra-chunk segments into one concrete `Path`.
+fn route_abstract(grid: &Grid, cache: &PathCache, start: Location, dest: Location) -> Option<Path> {
+    let start_chunk = chunk_of(&start, cache.config.chunk_size);
+    let dest_chunk = chunk_of(&dest, cache.config.chunk_size);
+
+    if start_chunk == dest_chunk {
+        return bfs_within(grid, start, dest);
+    }
+
+    let dest_gateways = cache.gateways.get(&dest_chunk)?;
+
+    let mut todo: MinHeap<u32, Location> = MinHeap::new();
+    let mut best_so_far: Map<Location, u32> = Map::new();
+    let mut came_from: Map<Location, Location> = Map::new();
+    let mut segments: Map<(Location, Location), Path> = Map::new();
+
+    best_so_far.insert(start, 0);
+    todo.push(start.distance_to(&dest), start);
+
+    while let Some((_, loc)) = todo.pop() {
+        if loc == dest {
+            return Some(stitch(&came_from, &segments, dest));
+        }
+
+        let cost_here = *best_so_far.get(&loc).unwrap();
+
+        let mut edges: Vec<(Location, u32, Path)> = if loc == start {
+            cache
+                .gateways
+                .get(&start_chunk)?
+                .iter()
+                .filter_map(|&gw| bfs_within(grid, start, gw).map(|p| (gw, path_cost(&p), p)))
+                .collect()
+        } else {
+            cache
+                .neighbors(&loc)
+                .iter()
+                .filter_map(|&(to, cost, ref cached)| {
+                    let path = match cached {
+                        Some(p) => p.clone(),
+                        None => bfs_within(grid, loc, to)?,
+                    };
+                    Some((to, cost, path))
+                })
+                .collect()
+        };
+
+        if dest_gateways.contains(&loc) {
+            if let Some(p) = bfs_within(grid, loc, dest) {
+                edges.push((dest, path_cost(&p), p));
+            }
+        }
+
+        for (next, edge_cost, path) in edges {
+            let next_cost = cost_here + edge_cost;
+            let better = best_so_far.get(&next).map_or(true, |&old| next_cost < old);
+            if better {
+                best_so_far.insert(next, next_cost);
+                came_from.insert(next, loc);
+                segments.insert((loc, next), path);
+                todo.push(next_cost + next.distance_to(&dest), next);
+            }
+        }
+    }
+
+    None
+}
+
+fn stitch(
+    came_from: &Map<Location, Location>,
+    segments: &Map<(Location, Location), Path>,
+    dest: Location,
+) -> Path {
+    let mut chunks = Vec::new();
+    let mut cur = dest;
+    while let Some(&prev) = came_from.get(&cur) {
+        chunks.push(segments.get(&(prev, cur)).unwrap().clone());
+        cur = prev;
+    }
+    chunks.reverse();
+
+    let mut path = Vec::new();
+    for (i, segment) in chunks.into_iter().enumerate() {
+        if i == 0 {
+            path.extend(segment);
+        } else {
+            // the first location of each later segment duplicates the previous
+            // segment's last location
+            path.extend(segment.into_iter().skip(1));
+        }
+    }
+    path
+}
+
+/// Routes `droplets` in priority order using the chunked abstract planner, falling back to
+/// the full space-time `route_one` only for droplets whose stitched path the `AvoidanceSet`
+/// rejects.
+pub(crate) fn route_many_chunked(
+    droplets: &[(&DropletId, &Droplet)],
+    grid: &Grid,
+    cache: &PathCache,
+    distance_cache: &DistanceCache,
+) -> Option<Map<DropletId, Path>> {
+    let mut av_set = AvoidanceSet::default();
+    let mut paths = Map::new();
+
+    for &(&id, droplet) in droplets.iter() {
+        let dest = droplet.destination.unwrap_or(droplet.location);
+        let abstract_path = route_abstract(grid, cache, droplet.location, dest)?;
+        let path = repair_conflicts(grid, droplet, dest, &abstract_path, &av_set, distance_cache)?;
+
+        av_set.avoid_path(&path, grid, &droplet.dimensions);
+        paths.insert(id, path);
+    }
+
+    Some(paths)
+}
+
+/// Checks a stitched abstract path against `av_set`; if any step collides, the whole droplet
+/// is re-routed with the existing full space-time search, which is guaranteed to respect
+/// `av_set`. The common case (no conflict) is just a cheap walk over the stitched path.
+fn repair_conflicts(
+    grid: &Grid,
+    droplet: &Droplet,
+    dest: Location,
+    path: &Path,
+    av_set: &AvoidanceSet,
+    distance_cache: &DistanceCache,
+) -> Option<Path> {
+    let last_t = path.len() - 1;
+    let has_conflict = path.iter().enumerate().any(|(t, &location)| {
+        let node = Node {
+            location,
+            time: t as Time,
+        };
+        // a droplet resting at its destination can still collide with a droplet that
+        // passes through that cell at some later timestep than this path's own length, so
+        // the final node needs the same would_finally_collide check route_many's done_fn
+        // uses, not just av_set.filter's in-range check
+        av_set.filter(vec![(0, node)]).is_empty()
+            || (t == last_t && av_set.would_finally_collide(&node))
+    });
+
+    if !has_conflict {
+        return Some(path.clone());
+    }
+
+    // reuse the GridView-wide static-distance table instead of recomputing a fresh reverse
+    // BFS from `dest` for every droplet `route_many_chunked` has to repair
+    let distances = distance_cache.get_or_compute(grid, dest);
+    let num_cells = grid.locations().count();
+    route_one(
+        droplet,
+        num_cells as Time + path.len() as Time,
+        &distances,
+        RouteMode::AStar,
+        None,
+        |node| av_set.filter(node.expand(grid)),
+        |node| node.location == dest && !av_set.would_finally_collide(node),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use env_logger;
+    use proptest::prelude::*;
+
+    use grid::grid::tests::arb_grid;
+    use grid::gridview::tests::arb_gridview;
+    use plan::route::paths_to_actions;
+
+    proptest! {
+        #[test]
+        fn chunked_routes_are_collision_free(
+            ref gv in arb_grid(5..10, 5..10, 0.95)
+                .prop_filter("not connected", |ref g| g.is_connected())
+                .prop_flat_map(|g| arb_gridview(g, 0..4))
+                .prop_filter("starting collision", |ref gv| gv.get_collision().is_none())
+                .prop_filter("ending collision", |ref gv| gv.get_destination_collision().is_none())
+        ) {
+            let _ = env_logger::try_init();
+            let mut gv = gv.clone();
+            if let Some(paths) = gv.route_chunked(ChunkConfig::default()) {
+                for a in &paths_to_actions(paths) {
+                    gv.execute(a);
+                }
+            }
+        }
+    }
+}
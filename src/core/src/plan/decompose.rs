@@ -0,0 +1,211 @@
+//! Decompose-and-merge routing for batches of mostly-independent droplets.
+//!
+//! In `route_with_config`, a single hard-to-route droplet late in the priority order forces
+//! a full restart of the whole batch. This module partitions droplets into groups whose
+//! start/destination regions never overlap (grown the same way `GridView::get_collision`
+//! pads droplet footprints, via `Grid::neighbors_dimensions`), routes each group
+//! independently, and merges the resulting paths. Grouping only looks at start/destination
+//! footprints, not the cells a route passes through in between, so two droplets in different
+//! groups can still collide mid-route at a shared corridor or bottleneck; `route_decomposed`
+//! checks the merged paths for exactly that before returning them, and falls back to the
+//! monolithic search if it finds one. Only the group that actually fails to route needs to be
+//! reshuffled and retried, not the whole batch.
+
+use grid::{Droplet, DropletId, Grid, GridView, Location};
+use plan::route::{route_subset, DistanceCache, Path, RouteConfig};
+use util::collections::{Map, Set};
+
+use rayon::prelude::*;
+
+struct DisjointSet {
+    parent: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(n: usize) -> DisjointSet {
+        DisjointSet {
+            parent: (0..n).collect(),
+        }
+    }
+
+    fn find(&mut self, x: usize) -> usize {
+        if self.parent[x] != x {
+            let root = self.find(self.parent[x]);
+            self.parent[x] = root;
+        }
+        self.parent[x]
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            self.parent[ra] = rb;
+        }
+    }
+}
+
+/// The cells a droplet could plausibly occupy while routing: its start and destination
+/// footprints, each grown by `neighbors_dimensions` the same way `GridView::get_collision`
+/// pads droplets to detect near-misses.
+fn droplet_region(grid: &Grid, droplet: &Droplet) -> Set<Location> {
+    let mut region: Set<Location> = grid
+        .neighbors_dimensions(&droplet.location, &droplet.dimensions)
+        .into_iter()
+        .collect();
+
+    if let Some(dest) = droplet.destination {
+        for loc in grid.neighbors_dimensions(&dest, &droplet.dimensions) {
+            region.insert(loc);
+        }
+    }
+
+    region
+}
+
+/// Groups droplets whose start/destination regions transitively overlap. Droplets in
+/// different groups rarely collide, since their regions are disjoint, but the regions only
+/// cover where each droplet starts and ends, not the cells its route passes through in
+/// between, so a corridor shared by two otherwise-disjoint groups can still see a collision;
+/// `route_decomposed` checks for that after merging instead of trusting groups to be fully
+/// independent.
+fn partition_into_groups<'a>(
+    grid: &Grid,
+    droplets: &[(&'a DropletId, &'a Droplet)],
+) -> Vec<Vec<(&'a DropletId, &'a Droplet)>> {
+    let regions: Vec<Set<Location>> = droplets
+        .iter()
+        .map(|&(_, d)| droplet_region(grid, d))
+        .collect();
+
+    let mut dsu = DisjointSet::new(droplets.len());
+    for i in 0..droplets.len() {
+        for j in (i + 1)..droplets.len() {
+            if regions[i].iter().any(|loc| regions[j].contains(loc)) {
+                dsu.union(i, j);
+            }
+        }
+    }
+
+    let mut groups: Map<usize, Vec<(&'a DropletId, &'a Droplet)>> = Map::new();
+    for (i, &droplet) in droplets.iter().enumerate() {
+        let root = dsu.find(i);
+        groups.entry(root).or_insert_with(Vec::new).push(droplet);
+    }
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+impl GridView {
+    /// Like `route_with_config`, but first splits the droplets into independent groups by
+    /// non-overlapping start/destination regions and routes each group on its own, merging
+    /// the results. Falls back to the monolithic search when the grid is congested enough
+    /// that every droplet ends up in one giant group anyway, or when the merged paths turn
+    /// out to collide across groups despite their regions being disjoint (see the module
+    /// docs: disjoint start/destination regions don't guarantee disjoint routes).
+    pub fn route_decomposed(&self, config: &RouteConfig) -> Option<Map<DropletId, Path>> {
+        let droplets = self.droplets.iter().collect::<Vec<_>>();
+        let groups = partition_into_groups(&self.grid, &droplets);
+
+        if groups.len() <= 1 {
+            return self.route_with_config(config);
+        }
+
+        let route_group = |group: &Vec<(&DropletId, &Droplet)>| {
+            route_subset(group, &self.grid, &self.distance_cache, config)
+        };
+
+        let results: Vec<Option<Map<DropletId, Path>>> = if config.parallel {
+            groups.par_iter().map(route_group).collect()
+        } else {
+            groups.iter().map(route_group).collect()
+        };
+
+        let mut merged = Map::new();
+        for result in results {
+            merged.extend(result?);
+        }
+
+        if paths_collide(&self.grid, &droplets, &merged) {
+            return self.route_with_config(config);
+        }
+
+        Some(merged)
+    }
+}
+
+/// The location `path` occupies at time `t`, holding at its last location once `t` runs past
+/// the end of the path (a droplet that finished its route stays put).
+fn location_at(path: &Path, t: usize) -> Location {
+    path[t.min(path.len() - 1)]
+}
+
+/// Safety net for `route_decomposed`: groups are split by disjoint start/destination
+/// regions, but two droplets in different groups can still cross paths at a shared corridor
+/// cell that neither region covers. Walks every pair of droplets in different collision
+/// groups timestep by timestep and checks whether their footprints, both grown via
+/// `neighbors_dimensions`, ever overlap. This is stricter than `GridView::get_collision`,
+/// which grows only one droplet of each pair.
+fn paths_collide(
+    grid: &Grid,
+    droplets: &[(&DropletId, &Droplet)],
+    paths: &Map<DropletId, Path>,
+) -> bool {
+    let max_t = paths.values().map(|p| p.len()).max().unwrap_or(0);
+
+    for &(&id1, d1) in droplets.iter() {
+        for &(&id2, d2) in droplets.iter() {
+            if id1 == id2 || d1.collision_group == d2.collision_group {
+                continue;
+            }
+
+            let (path1, path2) = match (paths.get(&id1), paths.get(&id2)) {
+                (Some(p1), Some(p2)) => (p1, p2),
+                _ => continue,
+            };
+
+            for t in 0..max_t {
+                let loc1 = location_at(path1, t);
+                let footprint2 = grid.neighbors_dimensions(&location_at(path2, t), &d2.dimensions);
+                if grid
+                    .neighbors_dimensions(&loc1, &d1.dimensions)
+                    .iter()
+                    .any(|loc| footprint2.contains(loc))
+                {
+                    return true;
+                }
+            }
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use env_logger;
+    use proptest::prelude::*;
+
+    use grid::grid::tests::arb_grid;
+    use grid::gridview::tests::arb_gridview;
+    use plan::route::paths_to_actions;
+
+    proptest! {
+        #[test]
+        fn decomposed_routes_are_collision_free(
+            ref gv in arb_grid(5..10, 5..10, 0.95)
+                .prop_filter("not connected", |ref g| g.is_connected())
+                .prop_flat_map(|g| arb_gridview(g, 0..4))
+                .prop_filter("starting collision", |ref gv| gv.get_collision().is_none())
+                .prop_filter("ending collision", |ref gv| gv.get_destination_collision().is_none())
+        ) {
+            let _ = env_logger::try_init();
+            let mut gv = gv.clone();
+            if let Some(paths) = gv.route_decomposed(&RouteConfig::default()) {
+                for a in &paths_to_actions(paths) {
+                    gv.execute(a);
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,11 @@
 
 mod place;
-mod route;
+pub(crate) mod route;
+pub(crate) mod chunked;
+mod decompose;
 mod minheap;
 pub mod plan;
 
 pub use self::plan::{Planner, PlanError};
-pub use self::route::Path;
+pub use self::route::{Path, RouteConfig, RouteMode};
+pub use self::chunked::ChunkConfig;
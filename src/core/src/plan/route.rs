@@ -1,15 +1,46 @@
-use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::Mutex;
 
-use plan::minheap::MinHeap;
 use grid::{Droplet, DropletId, Grid, GridView, Location};
 use exec::Action;
+use plan::minheap::MinHeap;
 
 use util::collections::{Map, Set};
 use util::collections::Entry::*;
 
-use rand::{thread_rng, Rng};
+use rand::{IsaacRng, Rng};
+use rayon::prelude::*;
+
+pub(crate) type Path = Vec<Location>;
+
+/// A table of true grid distances (in `expand`'s 100-per-step cost units) from every
+/// reachable cell to a single destination, computed by a reverse BFS over `neighbors4`
+/// that ignores time and other droplets. This is a tighter, still-admissible replacement
+/// for the Manhattan-distance heuristic: it never overestimates the real routing cost
+/// (it only omits the wait action and droplet-avoidance detours, both of which can only
+/// add cost), but it sees walls and islands that Manhattan distance cannot.
+///
+/// A `Location` missing from the table is unreachable from `dest` on the static grid, so
+/// its presence doubles as a fast unreachability check before space-time search begins.
+pub(crate) fn static_distances(grid: &Grid, dest: Location) -> Map<Location, Cost> {
+    let mut dist: Map<Location, Cost> = Map::new();
+    let mut frontier = VecDeque::new();
+
+    dist.insert(dest, 0);
+    frontier.push_back(dest);
+
+    while let Some(loc) = frontier.pop_front() {
+        let cost = dist[&loc];
+        for next in grid.neighbors4(&loc) {
+            if let Vacant(entry) = dist.entry(next) {
+                entry.insert(cost + 100);
+                frontier.push_back(next);
+            }
+        }
+    }
 
-type Path = Vec<Location>;
+    dist
+}
 
 fn build_path(mut came_from: Map<Node, Node>, end_node: Node) -> Path {
     let mut path = Vec::new();
@@ -41,24 +72,24 @@ pub fn paths_to_actions(paths: Map<DropletId, Path>) -> Vec<Action> {
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
-struct Node {
-    location: Location,
-    time: Time,
+pub(crate) struct Node {
+    pub(crate) location: Location,
+    pub(crate) time: Time,
 }
 
-type Time = u32;
-type Cost = u32;
-type NextVec = Vec<(Cost, Node)>;
+pub(crate) type Time = u32;
+pub(crate) type Cost = u32;
+pub(crate) type NextVec = Vec<(Cost, Node)>;
 
 #[derive(Default)]
-struct AvoidanceSet {
+pub(crate) struct AvoidanceSet {
     max_time: Time,
     present: Set<Node>,
     finals: Map<Location, Time>,
 }
 
 impl AvoidanceSet {
-    fn filter(&self, vec: NextVec) -> NextVec {
+    pub(crate) fn filter(&self, vec: NextVec) -> NextVec {
         vec.into_iter()
             .filter(|&(_cost, node)|
                     // make sure that it's either not in the map
@@ -78,7 +109,7 @@ impl AvoidanceSet {
             .map_or(false, |&final_t| node.time >= final_t)
     }
 
-    fn would_finally_collide(&self, node: &Node) -> bool {
+    pub(crate) fn would_finally_collide(&self, node: &Node) -> bool {
         (node.time..self.max_time)
             .map(|t| Node {
                 time: t,
@@ -87,7 +118,7 @@ impl AvoidanceSet {
             .any(|future_node| self.collides(&future_node))
     }
 
-    fn avoid_path(&mut self, path: &Path, grid: &Grid, droplet_dimensions: &Location) {
+    pub(crate) fn avoid_path(&mut self, path: &Path, grid: &Grid, droplet_dimensions: &Location) {
         let node_path = path.clone().into_iter().enumerate().map(|(i, loc)| Node {
             time: i as Time,
             location: loc,
@@ -125,7 +156,7 @@ impl Node {
     /// Returns a vector representing possible locations on the given `Grid` that can be the next
     /// location for this `Node`. This uses `neighbors4`, since droplets only move in the cardinal
     /// directions.
-    fn expand(&self, grid: &Grid) -> NextVec {
+    pub(crate) fn expand(&self, grid: &Grid) -> NextVec {
         let mut vec: Vec<(Cost, Node)> = grid.neighbors4(&self.location)
             .iter()
             .map(|&location| {
@@ -151,24 +182,219 @@ impl Node {
     }
 }
 
+/// Which search strategy `route_one` uses to order its open frontier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteMode {
+    /// Full space-time A*: orders by accumulated cost plus the static-distance heuristic.
+    /// Optimal and, with no beam width, complete; this is the default.
+    AStar,
+    /// Orders purely by the static-distance heuristic, ignoring accumulated cost. Faster
+    /// and lighter, but the resulting path is not guaranteed to be shortest.
+    GreedyBestFirst,
+    /// Ignores the heuristic entirely and orders by accumulated cost alone (uniform-cost
+    /// search).
+    Bfs,
+}
+
+impl Default for RouteMode {
+    fn default() -> RouteMode {
+        RouteMode::AStar
+    }
+}
+
+/// Configures `GridView::route_with_config`'s search over droplet priority orderings, and
+/// the per-droplet space-time search `route_one` performs within each ordering.
+#[derive(Debug, Clone, Copy)]
+pub struct RouteConfig {
+    /// Upper bound on how many priority orderings to try before giving up.
+    pub max_orderings: usize,
+    /// Evaluate orderings concurrently with rayon, taking the first one that succeeds.
+    pub parallel: bool,
+    /// Deterministically picks the random jumps `route_subset` interleaves with its
+    /// lexicographic walk. `None` falls back to a fixed default seed rather than skipping
+    /// the jumps entirely: starting from droplets sorted by `DropletId` and only ever
+    /// advancing lexicographically from there means a bounded `max_orderings` budget never
+    /// reorders anything but the highest-id droplets. Fixing a seed (default or explicit)
+    /// keeps tests that exercise `route` reproducible.
+    pub seed: Option<u64>,
+    /// The search strategy `route_one` uses.
+    pub mode: RouteMode,
+    /// If set, after expanding each time step `route_one` keeps only the best `k` nodes by
+    /// estimated cost and discards the rest, bounding memory at the risk of incompleteness.
+    /// `None` (the default) keeps the full frontier.
+    pub beam_width: Option<usize>,
+}
+
+impl Default for RouteConfig {
+    fn default() -> RouteConfig {
+        RouteConfig {
+            max_orderings: 50,
+            parallel: true,
+            seed: None,
+            mode: RouteMode::AStar,
+            beam_width: None,
+        }
+    }
+}
+
+/// Advances `indices` to the lexicographically next permutation, returning `false` (and
+/// leaving `indices` unchanged) once the last permutation has been reached.
+fn next_permutation(indices: &mut [usize]) -> bool {
+    let n = indices.len();
+    if n < 2 {
+        return false;
+    }
+
+    let mut i = n - 1;
+    while i > 0 && indices[i - 1] >= indices[i] {
+        i -= 1;
+    }
+    if i == 0 {
+        return false;
+    }
+
+    let mut j = n - 1;
+    while indices[j] <= indices[i - 1] {
+        j -= 1;
+    }
+
+    indices.swap(i - 1, j);
+    indices[i..].reverse();
+    true
+}
+
 impl GridView {
     pub fn route(&self) -> Option<Map<DropletId, Path>> {
-        let mut droplets = self.droplets.iter().collect::<Vec<_>>();
-        let mut rng = thread_rng();
-        for i in 1..50 {
-            rng.shuffle(&mut droplets);
-            let result = route_many(&droplets, &self.grid);
-            if result.is_some() {
-                return result;
+        self.route_with_config(&RouteConfig::default())
+    }
+
+    /// Like `route`, but replaces the random-restart search over droplet priority orderings
+    /// with a deterministic, exhaustive (up to `config.max_orderings`) enumeration of
+    /// permutations, optionally evaluated in parallel across threads.
+    pub fn route_with_config(&self, config: &RouteConfig) -> Option<Map<DropletId, Path>> {
+        let droplets = self.droplets.iter().collect::<Vec<_>>();
+        route_subset(&droplets, &self.grid, &self.distance_cache, config)
+    }
+}
+
+/// How many orderings each lexicographic walk of `route_subset` covers (its starting ordering
+/// plus `JUMP_EVERY - 1` advances) before jumping to a fresh random point in the permutation
+/// space. Without these jumps, a bounded `max_orderings`
+/// budget enumerated purely lexicographically from one starting point only ever reorders the
+/// last few droplets of that ordering, so a droplet stuck early in priority never gets tried
+/// later within the budget.
+const JUMP_EVERY: usize = 5;
+
+/// How many times a random jump is retried against an already-seen ordering before giving up
+/// and accepting the duplicate. A duplicate just wastes one of `max_orderings`' tries, it
+/// doesn't affect correctness, so this only needs to bound the retry loop, not guarantee an
+/// unseen ordering is always found.
+const MAX_JUMP_ATTEMPTS: usize = 20;
+
+/// Whether every permutation of `n` elements fits within `max_orderings`, computed so it can
+/// never overflow for a large `n` (the exact value only matters when it's small).
+fn permutations_fit(n: usize, max_orderings: usize) -> bool {
+    let mut total: usize = 1;
+    for i in 2..=n {
+        total = match total.checked_mul(i) {
+            Some(t) if t <= max_orderings => t,
+            _ => return false,
+        };
+    }
+    true
+}
+
+/// Searches `config.max_orderings` priority orderings of `droplets` for one `route_many` can
+/// fully route, trying orderings in parallel across threads when `config.parallel` is set.
+/// When the whole permutation space fits within `max_orderings`, it's enumerated
+/// exhaustively. Otherwise, orderings are generated by interleaving short lexicographic walks
+/// with random jumps (seeded by `config.seed`, or a fixed default seed), each jump retried
+/// against the orderings already tried, so a bounded budget samples broadly across the whole
+/// permutation space instead of wasting tries on repeats or only ever exploring a thin
+/// lexicographic slice of it. Factored out of `GridView::route_with_config` so
+/// `route_decomposed` can run the same search per group.
+pub(crate) fn route_subset(
+    droplets: &[(&DropletId, &Droplet)],
+    grid: &Grid,
+    distance_cache: &DistanceCache,
+    config: &RouteConfig,
+) -> Option<Map<DropletId, Path>> {
+    let mut order: Vec<usize> = (0..droplets.len()).collect();
+    order.sort_by_key(|&i| droplets[i].0.id);
+
+    let mut orderings = Vec::with_capacity(config.max_orderings);
+
+    if permutations_fit(order.len(), config.max_orderings) {
+        orderings.push(order.clone());
+        while next_permutation(&mut order) {
+            orderings.push(order.clone());
+        }
+    } else {
+        let mut rng = IsaacRng::new_from_u64(config.seed.unwrap_or(1));
+        rng.shuffle(&mut order);
+
+        let mut seen: Set<Vec<usize>> = Set::new();
+        seen.insert(order.clone());
+        orderings.push(order.clone());
+
+        while orderings.len() < config.max_orderings {
+            let advanced = orderings.len() % JUMP_EVERY != 0 && next_permutation(&mut order);
+            if !advanced || seen.get(&order).is_some() {
+                for _ in 0..MAX_JUMP_ATTEMPTS {
+                    rng.shuffle(&mut order);
+                    if seen.get(&order).is_none() {
+                        break;
+                    }
+                }
             }
-            trace!("route failed, trying iteration {}", i);
+            seen.insert(order.clone());
+            orderings.push(order.clone());
         }
+    }
+
+    let try_ordering = |order: &Vec<usize>| -> Option<Map<DropletId, Path>> {
+        let ordered: Vec<(&DropletId, &Droplet)> = order.iter().map(|&i| droplets[i]).collect();
+        route_many(&ordered, grid, distance_cache, config.mode, config.beam_width)
+    };
+
+    if config.parallel {
+        orderings.par_iter().find_map_any(try_ordering)
+    } else {
+        orderings.iter().find_map(try_ordering)
+    }
+}
+
+/// Per-destination static distance tables, shared across the repeated `route_many` calls
+/// that `GridView::route` makes while it retries different droplet orderings. Every
+/// retry routes the same droplets to the same destinations, so the reverse BFS only
+/// needs to run once per distinct destination. A `Mutex` (rather than a `RefCell`) guards
+/// the cache so that `route_with_config`'s parallel orderings can share it across threads.
+#[derive(Default, Debug)]
+pub(crate) struct DistanceCache(Mutex<Map<Location, Map<Location, Cost>>>);
+
+impl Clone for DistanceCache {
+    fn clone(&self) -> DistanceCache {
+        DistanceCache(Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
 
-        None
+impl DistanceCache {
+    pub(crate) fn get_or_compute(&self, grid: &Grid, dest: Location) -> Map<Location, Cost> {
+        let mut cache = self.0.lock().unwrap();
+        cache
+            .entry(dest)
+            .or_insert_with(|| static_distances(grid, dest))
+            .clone()
     }
 }
 
-fn route_many(droplets: &[(&DropletId, &Droplet)], grid: &Grid) -> Option<Map<DropletId, Path>> {
+fn route_many(
+    droplets: &[(&DropletId, &Droplet)],
+    grid: &Grid,
+    distance_cache: &DistanceCache,
+    mode: RouteMode,
+    beam_width: Option<usize>,
+) -> Option<Map<DropletId, Path>> {
     let mut av_set = AvoidanceSet::default();
     let num_cells = grid.locations().count();
 
@@ -176,17 +402,32 @@ fn route_many(droplets: &[(&DropletId, &Droplet)], grid: &Grid) -> Option<Map<Dr
     let mut max_t = 0;
 
     for &(&id, droplet) in droplets.iter() {
+        let dest = match droplet.destination {
+            Some(x) => x,
+            None => droplet.location,
+        };
+        let distances = distance_cache.get_or_compute(grid, dest);
+
+        // if the destination's reverse BFS never reached the droplet's start, there is
+        // no path on the static grid at all, so fail fast without running A*
+        if !distances.contains_key(&droplet.location) {
+            trace!(
+                "droplet {} cannot reach {} on the static grid",
+                droplet.id.id,
+                dest
+            );
+            return None;
+        }
+
         // route a single droplet
         let result = route_one(
             &droplet,
             num_cells as Time + max_t,
+            &distances,
+            mode,
+            beam_width,
             |node| av_set.filter(node.expand(grid)),
-            |node| {
-                node.location == match droplet.destination {
-                    Some(x) => x,
-                    None => droplet.location,
-                } && !av_set.would_finally_collide(node)
-            },
+            |node| node.location == dest && !av_set.would_finally_collide(node),
         );
         let path = match result {
             None => return None,
@@ -203,9 +444,20 @@ fn route_many(droplets: &[(&DropletId, &Droplet)], grid: &Grid) -> Option<Map<Dr
     Some(paths)
 }
 
-fn route_one<FNext, FDone>(
+/// Runs the space-time search for a single droplet as a true priority-first search: `todo`
+/// always holds the full open frontier ranked by `mode`'s key, and each step pops and expands
+/// only the single best-ranked node, so an admissible heuristic (`RouteMode::AStar`) actually
+/// prunes the search instead of flooding every reachable `(location, time)` pair. `beam_width`,
+/// if set, is enforced only at layer boundaries (when the popped node's `time` advances past
+/// every node already expanded): the open frontier is drained, cut down to the best `k` nodes,
+/// and the rest are discarded from both `todo` and `best_so_far`, bounding memory at the cost
+/// of completeness.
+pub(crate) fn route_one<FNext, FDone>(
     droplet: &Droplet,
     max_time: Time,
+    distances: &Map<Location, Cost>,
+    mode: RouteMode,
+    beam_width: Option<usize>,
     mut next_fn: FNext,
     mut done_fn: FDone,
 ) -> Option<Path>
@@ -214,81 +466,139 @@ where
     FDone: FnMut(&Node) -> bool,
 {
     trace!(
-        "Routing droplet {} from {} to {}",
+        "Routing droplet {} from {} to {} (mode {:?})",
         droplet.id.id,
         droplet.location,
         droplet
             .destination
-            .map_or("nowhere".into(), |dst| format!("{}", dst))
+            .map_or("nowhere".into(), |dst| format!("{}", dst)),
+        mode
     );
 
-    let mut todo: MinHeap<Cost, Node> = MinHeap::new();
-    let mut best_so_far: Map<Node, Cost> = Map::new();
-    let mut came_from: Map<Node, Node> = Map::new();
-    // TODO remove done in favor of came_from
-    let mut done: HashSet<Node> = HashSet::new();
+    // the precomputed static-distance oracle is a tighter admissible heuristic than
+    // Manhattan distance; a node whose location never shows up in the table can't reach
+    // the destination at all, so treat it as infinitely far away
+    let heuristic = |node: Node| -> Cost {
+        *distances.get(&node.location).unwrap_or(&Cost::max_value())
+    };
+
+    let rank = |mode: RouteMode, cost: Cost, node: Node| -> Cost {
+        match mode {
+            RouteMode::AStar => cost + heuristic(node),
+            RouteMode::GreedyBestFirst => heuristic(node),
+            RouteMode::Bfs => cost,
+        }
+    };
 
     let start_node = Node {
         location: droplet.location,
         time: 0,
     };
-    todo.push(0, start_node);
-    best_so_far.insert(start_node, 0);
 
-    let dest = match droplet.destination {
-        Some(x) => x,
-        None => droplet.location,
-    };
+    let mut best_so_far: Map<Node, Cost> = Map::new();
+    let mut came_from: Map<Node, Node> = Map::new();
+    let mut done: Set<Node> = Set::new();
+
+    best_so_far.insert(start_node, 0);
+    let mut todo: MinHeap<Cost, Node> = MinHeap::new();
+    todo.push(rank(mode, 0, start_node), start_node);
 
-    // use manhattan distance from goal as the heuristic
-    let heuristic = |node: Node| -> Cost { dest.distance_to(&node.location) };
+    let mut current_layer_time: Time = 0;
 
     while let Some((_, node)) = todo.pop() {
         if done_fn(&node) {
-            let path = build_path(came_from, node);
-            return Some(path);
+            return Some(build_path(came_from, node));
         }
 
-        // insert returns false if value was already there
-        if !done.insert(node) || node.time > max_time {
+        if done.get(&node).is_some() {
             continue;
         }
+        done.insert(node);
 
-        // node must be in best_so_far because it was inserted when we put it in
-        // the minheap
-        let node_cost: Cost = *best_so_far.get(&node).unwrap();
+        if node.time > max_time {
+            continue;
+        }
+
+        if node.time > current_layer_time {
+            current_layer_time = node.time;
+            if let Some(width) = beam_width {
+                prune_to_beam_width(&mut todo, &mut best_so_far, &mut came_from, &done, width);
+            }
+        }
 
+        let node_cost = *best_so_far.get(&node).unwrap();
         for (edge_cost, next) in next_fn(&node) {
-            if done.contains(&next) {
+            if done.get(&next).is_some() {
                 continue;
             }
 
-            let mut next_cost = node_cost + edge_cost;
-
-            match best_so_far.entry(next) {
-                Occupied(entry) => {
-                    let old_cost = *entry.get();
-                    if next_cost < old_cost {
-                        *entry.into_mut() = next_cost;
-                        came_from.insert(next, node);
-                    } else {
-                        next_cost = old_cost;
-                    }
-                }
-                Vacant(entry) => {
-                    entry.insert(next_cost);
-                    came_from.insert(next, node);
+            let next_cost = node_cost + edge_cost;
+            // greedy best-first doesn't use cost to pick a path at all, so once a node has
+            // been reached once there's nothing to gain by relaxing it again
+            let better = match mode {
+                RouteMode::GreedyBestFirst => !best_so_far.contains_key(&next),
+                RouteMode::AStar | RouteMode::Bfs => {
+                    best_so_far.get(&next).map_or(true, |&old| next_cost < old)
                 }
             };
 
-            let next_cost_est = next_cost + heuristic(next);
-            todo.push(next_cost_est, next)
+            if better {
+                best_so_far.insert(next, next_cost);
+                came_from.insert(next, node);
+                todo.push(rank(mode, next_cost, next), next);
+            }
         }
     }
 
     None
 }
 
+/// Drains `todo`'s open frontier (already sorted ascending by rank, since that's the order a
+/// `MinHeap` pops in), keeps the best `width` nodes, and pushes them back; any node cut from
+/// the frontier is also forgotten by `best_so_far`/`came_from` so it looks unvisited if a
+/// later, surviving node reaches it again.
+fn prune_to_beam_width(
+    todo: &mut MinHeap<Cost, Node>,
+    best_so_far: &mut Map<Node, Cost>,
+    came_from: &mut Map<Node, Node>,
+    done: &Set<Node>,
+    width: usize,
+) {
+    let mut frontier = Vec::new();
+    while let Some(entry) = todo.pop() {
+        frontier.push(entry);
+    }
+
+    // `todo` never removes a node's earlier, worse-ranked push once a cheaper one is found
+    // for it (lazy deletion), so the same Node can appear here more than once. Since the
+    // frontier is sorted ascending by rank, the first occurrence of each Node is always its
+    // best-ranked entry; drop every later duplicate before slicing at `width`; otherwise a
+    // stale duplicate cut here could wipe out the best_so_far/came_from entry its
+    // surviving, better-ranked twin still needs. A twin that was already popped and
+    // expanded is in `done`, so its leftover stale entries are dropped here as well.
+    let mut seen: Set<Node> = Set::new();
+    frontier.retain(|&(_, node)| {
+        if done.get(&node).is_some() || seen.get(&node).is_some() {
+            false
+        } else {
+            seen.insert(node);
+            true
+        }
+    });
+
+    if frontier.len() > width {
+        for &(_, dropped) in &frontier[width..] {
+            best_so_far.remove(&dropped);
+            came_from.remove(&dropped);
+        }
+        frontier.truncate(width);
+    }
+
+    for (rank, node) in frontier {
+        todo.push(rank, node);
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use env_logger;
@@ -300,6 +610,15 @@ pub mod tests {
     use grid::grid::tests::arb_grid;
     use grid::gridview::tests::arb_gridview;
 
+    #[test]
+    fn permutations_fit_is_exact_for_small_n() {
+        assert!(permutations_fit(0, 1));
+        assert!(permutations_fit(1, 1));
+        assert!(permutations_fit(3, 6));
+        assert!(!permutations_fit(3, 5));
+        assert!(!permutations_fit(20, 50));
+    }
+
     fn uncrowded_arch_from_grid(grid: Grid) -> BoxedStrategy<GridView> {
         let height = grid.vec.len();
         let width = grid.vec.iter().map(|row| row.len()).min().unwrap();
@@ -321,15 +640,20 @@ pub mod tests {
             // have to clone here so we can mutate gv later
             let droplet = gv.droplets.values().next().unwrap().clone();
             let num_cells = gv.grid.locations().count();
+            let dest = match droplet.destination {
+                Some(x) => x,
+                None => droplet.location,
+            };
+            let distances = static_distances(&gv.grid, dest);
 
             let path = route_one(
                 &droplet,
                 num_cells as Time,
+                &distances,
+                RouteMode::AStar,
+                None,
                 |node| node.expand(&gv.grid),
-                |node| node.location == match droplet.destination {
-                        Some(x) => x,
-                        None => droplet.location
-                    }
+                |node| node.location == dest
             ).unwrap();
 
             let mut path_map = Map::new();
@@ -364,5 +688,74 @@ pub mod tests {
                 gv.execute(a);
             }
         }
+
+        #[test]
+        fn static_distances_reach_every_cell_of_a_connected_grid(
+            ref grid in arb_grid(5..10, 5..10, 0.95)
+                .prop_filter("not connected", |ref g| g.is_connected())
+        ) {
+            let dest = grid.locations().next().unwrap().0;
+            let distances = static_distances(grid, dest);
+            prop_assert_eq!(distances.len(), grid.locations().count());
+            prop_assert_eq!(distances[&dest], 0);
+        }
+
+        #[test]
+        fn route_one_with_beam_width_connected(
+            ref gv in arb_grid(5..10, 5..10, 0.95)
+                .prop_filter("not connected", |ref g| g.is_connected())
+                .prop_flat_map(move |g| arb_gridview(g, 1..2)))
+        {
+            let _ = env_logger::try_init();
+            let gv = gv.clone();
+            let droplet = gv.droplets.values().next().unwrap().clone();
+            let num_cells = gv.grid.locations().count();
+            let dest = match droplet.destination {
+                Some(x) => x,
+                None => droplet.location,
+            };
+            let distances = static_distances(&gv.grid, dest);
+
+            // a beam narrow enough to actually cut the frontier, but wide enough that a
+            // connected grid is still always fully routable
+            for &mode in &[RouteMode::AStar, RouteMode::GreedyBestFirst, RouteMode::Bfs] {
+                let path = route_one(
+                    &droplet,
+                    num_cells as Time,
+                    &distances,
+                    mode,
+                    Some(2),
+                    |node| node.expand(&gv.grid),
+                    |node| node.location == dest
+                );
+                prop_assert!(path.is_some());
+            }
+        }
+
+        #[test]
+        fn route_with_beam_width_connected(
+            ref gv in arb_grid(5..10, 5..10, 0.95)
+                .prop_filter("not connected", |ref g| g.is_connected())
+                .prop_flat_map(uncrowded_arch_from_grid)
+                .prop_filter("starting collision",
+                             |ref gv| gv.get_collision().is_none())
+                .prop_filter("ending collision",
+                             |ref gv| gv.get_destination_collision().is_none())
+        )
+        {
+            let _ = env_logger::try_init();
+            let mut gv = gv.clone();
+            let config = RouteConfig {
+                beam_width: Some(4),
+                mode: RouteMode::GreedyBestFirst,
+                ..RouteConfig::default()
+            };
+
+            if let Some(paths) = gv.route_with_config(&config) {
+                for a in &paths_to_actions(paths) {
+                    gv.execute(a);
+                }
+            }
+        }
     }
 }